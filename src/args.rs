@@ -0,0 +1,206 @@
+//! Command-line option parsing, with environment-variable fallbacks so
+//! defaults can be set globally (e.g. in a shell profile) instead of typed
+//! out on every invocation.
+
+use std::env;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::backend::CopyBackend;
+
+const DEFAULT_JOBS: usize = 4;
+const DEFAULT_BUFFER_SIZE: usize = 10_000_000;
+
+pub struct Args {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub jobs: NonZeroUsize,
+    pub buffer_size: usize,
+    pub max_depth: Option<usize>,
+    pub backend: CopyBackend,
+    pub trash: bool,
+    pub preserve: bool,
+}
+
+impl Args {
+    /// Parses `raw` (as returned by `std::env::args`), falling back to
+    /// `MVV_JOBS`, `MVV_BUFFER_SIZE`, `MVV_MAX_DEPTH` and `MVV_BACKEND` for any
+    /// option not given on the command line. Returns a usage error - never
+    /// panics - on malformed input.
+    pub fn parse(raw: Vec<String>) -> Result<Self> {
+        let program = raw.first().cloned().unwrap_or_else(|| "mvv".into());
+        let usage = format!(
+            "usage: {program} [--backend tokio|uring] [--trash] [--preserve] \
+             [--jobs N] [--buffer-size BYTES] [--max-depth N] <source> <destination>"
+        );
+
+        let mut positional = Vec::new();
+        let mut jobs = None;
+        let mut buffer_size = None;
+        let mut max_depth = None;
+        let mut backend = None;
+        let mut trash = false;
+        let mut preserve = false;
+
+        let mut iter = raw.into_iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--jobs" => jobs = Some(take_value(&mut iter, "--jobs", &usage)?),
+                "--buffer-size" => buffer_size = Some(take_value(&mut iter, "--buffer-size", &usage)?),
+                "--max-depth" => max_depth = Some(take_value(&mut iter, "--max-depth", &usage)?),
+                "--backend" => backend = Some(take_value(&mut iter, "--backend", &usage)?),
+                "--trash" => trash = true,
+                "--preserve" => preserve = true,
+                other if other.starts_with("--backend=") => {
+                    backend = Some(other.trim_start_matches("--backend=").to_string())
+                }
+                other if other.starts_with("--") => {
+                    return Err(anyhow::anyhow!("unknown flag \"{other}\"\n{usage}"))
+                }
+                other => positional.push(other.to_string()),
+            }
+        }
+
+        if positional.len() != 2 {
+            return Err(anyhow::anyhow!("incorrect syntax\n{usage}"));
+        }
+
+        let jobs = jobs
+            .or_else(|| env::var("MVV_JOBS").ok())
+            .map(|v| parse_jobs(&v, &usage))
+            .transpose()?
+            .unwrap_or(NonZeroUsize::new(DEFAULT_JOBS).unwrap());
+
+        let buffer_size = buffer_size
+            .or_else(|| env::var("MVV_BUFFER_SIZE").ok())
+            .map(|v| parse_buffer_size(&v, &usage))
+            .transpose()?
+            .unwrap_or(DEFAULT_BUFFER_SIZE);
+
+        let max_depth = max_depth
+            .or_else(|| env::var("MVV_MAX_DEPTH").ok())
+            .map(|v| {
+                v.parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("--max-depth expects a non-negative integer\n{usage}"))
+            })
+            .transpose()?;
+
+        let backend = backend
+            .or_else(|| env::var("MVV_BACKEND").ok())
+            .map(|v| CopyBackend::parse(&v))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            source: PathBuf::from(&positional[0]),
+            destination: PathBuf::from(&positional[1]),
+            jobs,
+            buffer_size,
+            max_depth,
+            backend,
+            trash,
+            preserve,
+        })
+    }
+}
+
+fn take_value(
+    iter: &mut impl Iterator<Item = String>,
+    flag: &str,
+    usage: &str,
+) -> Result<String> {
+    iter.next()
+        .ok_or_else(|| anyhow::anyhow!("{flag} expects a value\n{usage}"))
+}
+
+fn parse_jobs(v: &str, usage: &str) -> Result<NonZeroUsize> {
+    v.parse::<NonZeroUsize>()
+        .map_err(|_| anyhow::anyhow!("--jobs expects a positive integer\n{usage}"))
+}
+
+fn parse_buffer_size(v: &str, usage: &str) -> Result<usize> {
+    v.parse::<NonZeroUsize>()
+        .map(NonZeroUsize::get)
+        .map_err(|_| anyhow::anyhow!("--buffer-size expects a positive integer\n{usage}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(args: &[&str]) -> Vec<String> {
+        std::iter::once("mvv")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn defaults_when_only_positional_args_given() {
+        let args = Args::parse(raw(&["src", "dest"])).unwrap();
+        assert_eq!(args.source, PathBuf::from("src"));
+        assert_eq!(args.destination, PathBuf::from("dest"));
+        assert_eq!(args.jobs.get(), DEFAULT_JOBS);
+        assert_eq!(args.buffer_size, DEFAULT_BUFFER_SIZE);
+        assert_eq!(args.max_depth, None);
+        assert_eq!(args.backend, CopyBackend::Tokio);
+        assert!(!args.trash);
+        assert!(!args.preserve);
+    }
+
+    #[test]
+    fn parses_all_flags() {
+        let args = Args::parse(raw(&[
+            "--backend",
+            "uring",
+            "--trash",
+            "--preserve",
+            "--jobs",
+            "8",
+            "--buffer-size",
+            "4096",
+            "--max-depth",
+            "2",
+            "src",
+            "dest",
+        ]))
+        .unwrap();
+        assert_eq!(args.backend, CopyBackend::IoUring);
+        assert!(args.trash);
+        assert!(args.preserve);
+        assert_eq!(args.jobs.get(), 8);
+        assert_eq!(args.buffer_size, 4096);
+        assert_eq!(args.max_depth, Some(2));
+    }
+
+    #[test]
+    fn rejects_wrong_positional_count() {
+        assert!(Args::parse(raw(&["only-one"])).is_err());
+        assert!(Args::parse(raw(&["one", "two", "three"])).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_flag() {
+        assert!(Args::parse(raw(&["--nonsense", "src", "dest"])).is_err());
+    }
+
+    #[test]
+    fn rejects_zero_jobs() {
+        assert!(Args::parse(raw(&["--jobs", "0", "src", "dest"])).is_err());
+    }
+
+    #[test]
+    fn rejects_zero_buffer_size() {
+        assert!(Args::parse(raw(&["--buffer-size", "0", "src", "dest"])).is_err());
+    }
+
+    #[test]
+    fn parse_buffer_size_rejects_non_positive_integers() {
+        assert!(parse_buffer_size("0", "usage").is_err());
+        assert!(parse_buffer_size("-1", "usage").is_err());
+        assert!(parse_buffer_size("not-a-number", "usage").is_err());
+        assert_eq!(parse_buffer_size("1024", "usage").unwrap(), 1024);
+    }
+}
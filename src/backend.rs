@@ -0,0 +1,247 @@
+//! Copy backends for `move_file`.
+//!
+//! The default backend streams bytes through `tokio::io::copy`, which on
+//! Linux hands every read/write off to tokio's blocking threadpool. The
+//! `io_uring` backend (behind the `io_uring` feature) submits real
+//! asynchronous SQEs instead, which matters for large-file throughput.
+
+use std::path::Path;
+
+use anyhow::Result;
+use indicatif::ProgressBar;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
+
+use crate::resume;
+
+/// Which I/O path `move_file` should use to stream bytes from `src` to `dest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyBackend {
+    /// `tokio::io::copy` over `tokio::fs::File` (always available).
+    #[default]
+    Tokio,
+    /// `tokio-uring` SQE-based copy loop (requires the `io_uring` feature and kernel support).
+    IoUring,
+}
+
+impl CopyBackend {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "tokio" => Ok(Self::Tokio),
+            "uring" => Ok(Self::IoUring),
+            other => Err(anyhow::anyhow!(
+                "unknown backend \"{other}\", expected \"tokio\" or \"uring\""
+            )),
+        }
+    }
+}
+
+/// The paths involved in one copy: `src_path` is the source, `write_path` is
+/// the temp file `move_file` streams into, and `dest_path` is the final
+/// destination it'll be renamed to - used only to key the resume sidecar, so
+/// checkpoints survive that rename.
+#[cfg_attr(not(feature = "io_uring"), allow(dead_code))]
+pub struct CopyPaths<'a> {
+    pub src: &'a Path,
+    pub write: &'a Path,
+    pub dest: &'a Path,
+}
+
+/// Copies `src_file` to `dest_file` (opened against `paths.write`) starting
+/// at their current seek positions, using `backend`. Falls back to the tokio
+/// path when `io_uring` isn't compiled in or the kernel doesn't support it.
+pub async fn copy_with_backend(
+    backend: CopyBackend,
+    #[cfg_attr(not(feature = "io_uring"), allow(unused_variables))] paths: CopyPaths<'_>,
+    src_file: tokio::fs::File,
+    #[cfg_attr(not(feature = "io_uring"), allow(unused_mut))] mut dest_file: tokio::fs::File,
+    buf_size: u64,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    match backend {
+        CopyBackend::Tokio => {
+            tokio_copy_with_checkpoint(src_file, dest_file, paths.src, paths.dest, buf_size, progress_bar)
+                .await
+        }
+        #[cfg(feature = "io_uring")]
+        CopyBackend::IoUring => {
+            let start_offset = dest_file.stream_position().await?;
+            drop(src_file);
+            drop(dest_file);
+            match uring::copy(paths.src, paths.write, paths.dest, start_offset, buf_size, progress_bar).await {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    progress_bar.set_message(format!(
+                        "io_uring copy failed ({e}), falling back to tokio"
+                    ));
+                    let src_file = tokio::fs::File::open(paths.src).await?;
+                    let dest_file = tokio::fs::File::options()
+                        .create(true)
+                        .truncate(false)
+                        .write(true)
+                        .open(paths.write)
+                        .await?;
+                    tokio_copy_with_checkpoint(
+                        src_file,
+                        dest_file,
+                        paths.src,
+                        paths.dest,
+                        buf_size,
+                        progress_bar,
+                    )
+                    .await
+                }
+            }
+        }
+        #[cfg(not(feature = "io_uring"))]
+        CopyBackend::IoUring => {
+            progress_bar.set_message("io_uring backend not compiled in, using tokio");
+            tokio_copy_with_checkpoint(src_file, dest_file, paths.src, paths.dest, buf_size, progress_bar)
+                .await
+        }
+    }
+}
+
+/// Copies in `buf_size` chunks, flushing and checkpointing the sidecar after
+/// each one, so an interrupted run can resume from the last flushed block
+/// instead of rescanning.
+async fn tokio_copy_with_checkpoint(
+    mut src_file: tokio::fs::File,
+    mut dest_file: tokio::fs::File,
+    src_path: &Path,
+    dest_path: &Path,
+    buf_size: u64,
+    progress_bar: &ProgressBar,
+) -> Result<()> {
+    let mut offset = dest_file.stream_position().await?;
+    let mut buf = vec![0u8; buf_size as usize];
+
+    loop {
+        let n = src_file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+
+        dest_file.write_all(&buf[..n]).await?;
+        dest_file.flush().await?;
+
+        offset += n as u64;
+        progress_bar.set_position(offset);
+        resume::checkpoint(src_path, dest_path, buf_size, offset).await?;
+    }
+
+    dest_file.sync_all().await?;
+    Ok(())
+}
+
+#[cfg(feature = "io_uring")]
+mod uring {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use tokio_uring::buf::BoundedBuf;
+    use tokio_uring::fs::{File as UringFile, OpenOptions};
+
+    /// Maximum number of in-flight read/write SQE pairs per file, on top of the
+    /// global `Semaphore` that bounds concurrent files.
+    const URING_IN_FLIGHT: usize = 4;
+
+    /// Streams `write` into `dest` (used only to key the resume sidecar) from
+    /// `start_offset`, using owned buffers handed back by the kernel, keeping
+    /// up to [`URING_IN_FLIGHT`] read/write pairs in flight, then fsyncs `dest`.
+    ///
+    /// `tokio_uring::start` spins up its own single-threaded io_uring runtime and
+    /// blocks the calling thread until the future it's given completes - it isn't
+    /// itself a future, and running it directly on a multithreaded tokio worker
+    /// would stall every other task scheduled on that thread for the whole
+    /// transfer. Instead it runs on a dedicated OS thread, with the result
+    /// bridged back to the async caller over a oneshot channel.
+    pub async fn copy(
+        src: &std::path::Path,
+        write: &std::path::Path,
+        dest: &std::path::Path,
+        start_offset: u64,
+        buf_size: u64,
+        progress_bar: &ProgressBar,
+    ) -> Result<()> {
+        let src = src.to_path_buf();
+        let write = write.to_path_buf();
+        let dest = dest.to_path_buf();
+        let progress_bar = progress_bar.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        std::thread::spawn(move || {
+            let result = tokio_uring::start(copy_blocking(
+                src,
+                write,
+                dest,
+                start_offset,
+                buf_size as usize,
+                progress_bar,
+            ));
+            let _ = tx.send(result);
+        });
+
+        rx.await?
+    }
+
+    async fn copy_blocking(
+        src: PathBuf,
+        write: PathBuf,
+        dest: PathBuf,
+        start_offset: u64,
+        buf_size: usize,
+        progress_bar: ProgressBar,
+    ) -> Result<()> {
+        let src_file = Rc::new(UringFile::open(&src).await?);
+        // `create(true)` without `truncate(true)` preserves whatever `write`
+        // already has on disk, since `start_offset` may be resuming partway
+        // through it.
+        let dest_file = Rc::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(&write)
+                .await?,
+        );
+
+        let mut next_read_offset = start_offset;
+        let mut eof = false;
+        let mut inflight: VecDeque<tokio::task::JoinHandle<Result<(u64, usize)>>> = VecDeque::new();
+
+        loop {
+            while !eof && inflight.len() < URING_IN_FLIGHT {
+                let src_file = Rc::clone(&src_file);
+                let dest_file = Rc::clone(&dest_file);
+                let offset = next_read_offset;
+                inflight.push_back(tokio_uring::spawn(async move {
+                    let buf = vec![0u8; buf_size];
+                    let (res, buf) = src_file.read_at(buf, offset).await;
+                    let n = res?;
+                    if n > 0 {
+                        let (res, _buf) = dest_file.write_at(buf.slice(..n), offset).submit().await;
+                        res?;
+                    }
+                    Ok((offset, n))
+                }));
+                next_read_offset += buf_size as u64;
+            }
+
+            let Some(handle) = inflight.pop_front() else {
+                break;
+            };
+            let (offset, n) = handle.await??;
+            if n == 0 {
+                eof = true;
+                continue;
+            }
+
+            let verified_offset = offset + n as u64;
+            progress_bar.set_position(verified_offset);
+            resume::checkpoint(&src, &dest, buf_size as u64, verified_offset).await?;
+        }
+
+        dest_file.sync_all().await?;
+        Ok(())
+    }
+}
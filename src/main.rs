@@ -1,21 +1,42 @@
-use std::io::ErrorKind;
+mod args;
+mod backend;
+mod metadata;
+mod rename;
+mod resume;
+mod scan;
+
 use std::sync::Arc;
-use std::{env::args, path::Path};
+use std::{env::args as env_args, path::Path};
 
 use anyhow::Result;
+use args::Args;
+use backend::CopyBackend;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _};
+use tokio::io::AsyncSeekExt as _;
 use tokio::sync::Semaphore;
-use walkdir::WalkDir;
 
-async fn move_file(
-    src_path: impl AsRef<Path>,
-    dest_path: impl AsRef<Path>,
+/// Run-wide settings shared by every `move_file` task, so the per-task
+/// signature doesn't have to grow a parameter for each new flag.
+struct RunConfig {
     buf_size: usize,
     multi_progress: Arc<MultiProgress>,
     permits: Arc<Semaphore>,
+    copy_backend: CopyBackend,
+    trash: bool,
+    preserve: bool,
+}
+
+async fn move_file(
+    src_path: impl AsRef<Path>,
+    dest_path: impl AsRef<Path>,
+    config: Arc<RunConfig>,
 ) -> Result<()> {
-    let _guard = permits.acquire().await?;
+    let _guard = config.permits.acquire().await?;
+    let buf_size = config.buf_size;
+    let trash = config.trash;
+    let preserve = config.preserve;
+    let multi_progress = &config.multi_progress;
+    let copy_backend = config.copy_backend;
 
     let progress_style = ProgressStyle::with_template(
         "[{binary_bytes_per_sec}] {wide_bar} {msg:70!} {bytes}/{total_bytes}",
@@ -39,72 +60,23 @@ async fn move_file(
         src_path.as_ref().file_name().unwrap().display()
     ));
 
-    let init_offset = if std::fs::exists(&dest_path)? {
-        let buf_size = buf_size / 2;
-        let mut src_buf = vec![0; buf_size];
-        let mut dest_buf = vec![0; buf_size];
-
-        let mut src_file = tokio::fs::File::open(&src_path).await?;
-        let src_size = src_file.metadata().await?.len() as usize;
-
-        let mut dest_file = tokio::fs::File::open(&dest_path).await?;
-        let dest_size = dest_file.metadata().await?.len() as usize;
+    // A same-filesystem rename is instant and skips the copy/progress path
+    // entirely. `--trash` bypasses it, since it needs the original unlink path.
+    if !trash && rename::try_fast_rename(src_path.as_ref(), dest_path.as_ref()).await? {
+        progress_bar.set_length(1);
+        progress_bar.set_position(1);
+        progress_bar.finish_with_message("complete (renamed)");
+        return Ok(());
+    }
 
-        let min_size = src_size.min(dest_size);
-        let mut read = 0;
+    let write_path = rename::temp_path_for(dest_path.as_ref());
 
-        progress_bar.set_length(min_size as u64);
+    let init_offset = if std::fs::exists(&write_path)? {
         progress_bar.set_message(format!(
             "checking \"{}\"",
             src_path.as_ref().file_name().unwrap().display()
         ));
-
-        if min_size != 0 {
-            while read < min_size {
-                let read_max_size = src_buf.len().min(min_size - read);
-
-                let curr_src_read = src_file.read(&mut src_buf[..read_max_size]).await?;
-                let curr_dest_read = dest_file.read(&mut dest_buf[..read_max_size]).await?;
-
-                if curr_src_read < curr_dest_read {
-                    if let Err(e) = src_file
-                        .read_exact(&mut src_buf[curr_src_read..curr_dest_read])
-                        .await
-                    {
-                        if e.kind() == ErrorKind::UnexpectedEof {
-                            break;
-                        }
-                        return Err(e.into());
-                    }
-                } else if curr_src_read > curr_dest_read {
-                    if let Err(e) = dest_file
-                        .read_exact(&mut dest_buf[curr_dest_read..curr_src_read])
-                        .await
-                    {
-                        if e.kind() == ErrorKind::UnexpectedEof {
-                            break;
-                        }
-                        return Err(e.into());
-                    }
-                }
-
-                let curr_read = curr_src_read.max(curr_dest_read);
-
-                for (&x, &y) in src_buf[..curr_read]
-                    .iter()
-                    .zip(dest_buf[..curr_read].iter())
-                {
-                    if x != y {
-                        break;
-                    }
-                    read += 1;
-                }
-
-                progress_bar.inc(curr_read as u64);
-            }
-        }
-
-        read as u64
+        resume::resume_offset(src_path.as_ref(), &write_path, dest_path.as_ref(), buf_size as u64).await?
     } else {
         0u64
     };
@@ -119,8 +91,9 @@ async fn move_file(
 
     let mut dest_file = tokio::fs::File::options()
         .create(true)
+        .truncate(false)
         .write(true)
-        .open(&dest_path)
+        .open(&write_path)
         .await?;
     dest_file
         .seek(std::io::SeekFrom::Start(init_offset))
@@ -133,77 +106,135 @@ async fn move_file(
         src_path.as_ref().file_name().unwrap().display()
     ));
 
-    tokio::io::copy(&mut src_file, &mut progress_bar.wrap_async_read(dest_file)).await?;
-    drop(src_file);
+    backend::copy_with_backend(
+        copy_backend,
+        backend::CopyPaths {
+            src: src_path.as_ref(),
+            write: &write_path,
+            dest: dest_path.as_ref(),
+        },
+        src_file,
+        dest_file,
+        buf_size as u64,
+        &progress_bar,
+    )
+    .await?;
+
+    tokio::fs::rename(&write_path, dest_path.as_ref()).await?;
+    resume::Sidecar::delete(dest_path.as_ref()).await?;
+
+    if preserve {
+        metadata::preserve(src_path.as_ref(), dest_path.as_ref()).await?;
+    }
 
-    tokio::fs::remove_file(src_path).await?;
+    delete_source(src_path.as_ref(), trash).await?;
 
     progress_bar.finish_with_message("complete");
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = args().collect::<Vec<_>>();
-    if args.len() != 3 && args.len() != 4 {
-        return Err(anyhow::anyhow!(
-            "incorrect syntax\nusage: {} <source> <destination> [paralleled-jobs]",
-            args[0]
-        ));
+/// Recreates `src`'s symlink target at `dest` and removes `src`, tolerating a
+/// `dest` that's already the right symlink - e.g. left behind by a prior run
+/// that recreated this symlink before getting interrupted elsewhere in the
+/// tree - instead of treating it as fatal.
+async fn recreate_symlink(src: &Path, dest: &Path, trash: bool) -> Result<()> {
+    let target = tokio::fs::read_link(src).await?;
+
+    if let Some(dest_parent) = dest.parent() {
+        tokio::fs::create_dir_all(dest_parent).await?;
     }
 
-    let paralleled_jobs = if args.len() == 4 {
-        args[3].parse().unwrap()
-    } else {
-        4
-    };
+    match tokio::fs::symlink(&target, dest).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            if tokio::fs::read_link(dest).await.ok().as_deref() != Some(target.as_path()) {
+                tokio::fs::remove_file(dest).await?;
+                tokio::fs::symlink(&target, dest).await?;
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    delete_source(src, trash).await
+}
 
-    let permits = Arc::new(Semaphore::new(paralleled_jobs));
+/// Removes `path`, either via a hard unlink or by routing it through the OS
+/// recycle bin when `trash` is set. The `trash` crate is synchronous, so it's
+/// run on the blocking threadpool to avoid stalling the runtime.
+async fn delete_source(path: &Path, trash: bool) -> Result<()> {
+    if trash {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || trash::delete(&path)).await??;
+    } else {
+        tokio::fs::remove_file(path).await?;
+    }
+    Ok(())
+}
 
-    let src_path = Path::new(&args[1]);
-    let dest_path = Path::new(&args[2]);
+#[tokio::main]
+async fn main() -> Result<()> {
+    let Args {
+        source,
+        destination,
+        jobs,
+        buffer_size,
+        max_depth,
+        backend: copy_backend,
+        trash,
+        preserve,
+    } = Args::parse(env_args().collect())?;
+
+    let permits = Arc::new(Semaphore::new(jobs.get()));
+
+    let src_path = source.as_path();
+    let dest_path = destination.as_path();
 
     let src_is_file = src_path.is_file();
 
     let multi_progress = Arc::new(MultiProgress::new());
 
+    let run_config = Arc::new(RunConfig {
+        buf_size: buffer_size,
+        multi_progress: Arc::clone(&multi_progress),
+        permits,
+        copy_backend,
+        trash,
+        preserve,
+    });
+
     let mut tasks = vec![];
 
-    for entry in WalkDir::new(src_path) {
-        let entry = entry?;
-        if entry.file_type().is_symlink() {
-            multi_progress.println(format!(
-                "warning: symlink \"{}\" is skipped",
-                entry.path().display()
-            ))?;
-        }
-        if !entry.file_type().is_file() {
-            continue;
-        }
-        let rel_path = Path::strip_prefix(entry.path(), src_path)?;
-        let (src_path, dest_path) = if src_is_file {
-            (src_path.to_path_buf(), dest_path.to_path_buf())
-        } else {
-            (
-                Path::join(src_path, rel_path),
-                Path::join(dest_path, rel_path),
-            )
-        };
-        if entry.file_type().is_file() {
-            tasks.push((
-                src_path.clone(),
-                tokio::spawn(move_file(
-                    src_path,
-                    dest_path,
-                    10_000_000,
-                    Arc::clone(&multi_progress),
-                    Arc::clone(&permits),
-                )),
-            ));
+    let summary_bar = multi_progress.add(ProgressBar::new_spinner());
+    summary_bar.enable_steady_tick(std::time::Duration::from_millis(100));
+    let (mut scanned_rx, scan_handle) = scan::spawn(
+        src_path.to_path_buf(),
+        dest_path.to_path_buf(),
+        src_is_file,
+        max_depth,
+        summary_bar,
+    );
+
+    let mut incomplete = false;
+
+    while let Some(entry) = scanned_rx.recv().await {
+        match entry {
+            scan::Entry::Symlink { src, dest } => {
+                if let Err(e) = recreate_symlink(&src, &dest, trash).await {
+                    incomplete = true;
+                    multi_progress
+                        .println(format!("error when moving symlink \"{}\": {}", src.display(), e))?;
+                }
+            }
+            scan::Entry::File { src, dest } => {
+                tasks.push((
+                    src.clone(),
+                    tokio::spawn(move_file(src, dest, Arc::clone(&run_config))),
+                ));
+            }
         }
     }
 
-    let mut incomplete = false;
+    scan_handle.await??;
 
     for (path, task) in tasks {
         if let Err(e) = task.await? {
@@ -219,9 +250,27 @@ async fn main() -> Result<()> {
     }
 
     if src_path.is_file() {
-        tokio::fs::remove_file(src_path).await?;
+        if let Err(e) = delete_source(src_path, trash).await {
+            multi_progress.println(format!(
+                "error when removing \"{}\": {}",
+                src_path.display(),
+                e
+            ))?;
+        }
     } else if src_path.is_dir() {
-        tokio::fs::remove_dir_all(src_path).await?;
+        let removal: Result<()> = if trash {
+            let dir = src_path.to_path_buf();
+            tokio::task::spawn_blocking(move || trash::delete(&dir)).await?.map_err(Into::into)
+        } else {
+            tokio::fs::remove_dir_all(src_path).await.map_err(Into::into)
+        };
+        if let Err(e) = removal {
+            multi_progress.println(format!(
+                "error when removing \"{}\": {}",
+                src_path.display(),
+                e
+            ))?;
+        }
     }
 
     multi_progress.println("move complete")?;
@@ -0,0 +1,58 @@
+//! Metadata preservation for `--preserve`: replays permissions and
+//! timestamps from a source file onto its freshly-written destination.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Copies `src`'s permission bits and mtime/atime onto `dest`. Ownership is
+/// intentionally left alone — `mvv` doesn't run as root in the common case,
+/// and `chown` would just fail there.
+pub async fn preserve(src: &Path, dest: &Path) -> Result<()> {
+    let metadata = tokio::fs::metadata(src).await?;
+    let permissions = metadata.permissions();
+    let dest = dest.to_path_buf();
+
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        std::fs::set_permissions(&dest, permissions)?;
+        set_times(&dest, metadata.accessed()?, metadata.modified()?)?;
+        Ok(())
+    })
+    .await?
+}
+
+/// Platform shim around `utimensat(2)` — std has no stable API for setting
+/// both atime and mtime on a file in one call.
+#[cfg(unix)]
+fn set_times(path: &Path, atime: std::time::SystemTime, mtime: std::time::SystemTime) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    fn to_timespec(t: std::time::SystemTime) -> libc::timespec {
+        let duration = t
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        libc::timespec {
+            tv_sec: duration.as_secs() as libc::time_t,
+            tv_nsec: duration.subsec_nanos() as i64,
+        }
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let times = [to_timespec(atime), to_timespec(mtime)];
+
+    // SAFETY: `c_path` is a valid NUL-terminated string for the duration of the call,
+    // and `times` points to two well-formed `timespec`s.
+    let ret = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            times.as_ptr(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Same-filesystem rename fast-path and the temp-file-then-rename pattern used
+//! when a streaming copy is unavoidable (cross-device, or any other case where
+//! `rename(2)` can't be used directly).
+
+use std::os::unix::fs::MetadataExt as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+/// Attempts a same-filesystem `rename(2)` from `src` to `dest`. Returns `Ok(true)`
+/// if the rename completed (the move is done, no copy needed), `Ok(false)` if the
+/// parents live on different filesystems and the caller should fall back to a
+/// streaming copy, or an error for anything else.
+pub async fn try_fast_rename(src: &Path, dest: &Path) -> Result<bool> {
+    let src_parent = src.parent().unwrap_or(src);
+    let dest_parent = dest.parent().unwrap_or(dest);
+
+    let src_dev = tokio::fs::metadata(src_parent).await?.dev();
+    let dest_dev = tokio::fs::metadata(dest_parent).await?.dev();
+
+    if src_dev != dest_dev {
+        return Ok(false);
+    }
+
+    match tokio::fs::rename(src, dest).await {
+        Ok(()) => Ok(true),
+        Err(e) if is_cross_device_error(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether `e` is the `EXDEV` a same-filesystem `rename(2)` can still raise
+/// despite the parents' `dev()` matching - e.g. on overlay/union filesystems
+/// where a single `st_dev` can span multiple underlying devices.
+fn is_cross_device_error(e: &std::io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EXDEV)
+}
+
+/// The sibling temp file a streaming copy writes to before being atomically
+/// renamed into place at `dest`, so a crash mid-copy never leaves a truncated
+/// file at the real destination path.
+pub fn temp_path_for(dest: &Path) -> PathBuf {
+    let file_name = dest.file_name().unwrap_or_default();
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".mvvtmp");
+    dest.with_file_name(temp_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_path_for_appends_suffix_to_the_file_name() {
+        assert_eq!(
+            temp_path_for(Path::new("/a/b/file.txt")),
+            PathBuf::from("/a/b/file.txt.mvvtmp")
+        );
+    }
+
+    #[test]
+    fn is_cross_device_error_matches_only_exdev() {
+        assert!(is_cross_device_error(&std::io::Error::from_raw_os_error(
+            libc::EXDEV
+        )));
+        assert!(!is_cross_device_error(&std::io::Error::from_raw_os_error(
+            libc::EACCES
+        )));
+        assert!(!is_cross_device_error(&std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no such file"
+        )));
+    }
+
+    #[tokio::test]
+    async fn try_fast_rename_moves_a_file_on_the_same_filesystem() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let src = dir.join(format!("mvv-rename-test-{pid}-src"));
+        let dest = dir.join(format!("mvv-rename-test-{pid}-dest"));
+        std::fs::write(&src, b"hello").unwrap();
+        let _ = std::fs::remove_file(&dest);
+
+        let renamed = try_fast_rename(&src, &dest).await.unwrap();
+
+        assert!(renamed);
+        assert!(!src.exists());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello");
+
+        std::fs::remove_file(&dest).unwrap();
+    }
+}
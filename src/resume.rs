@@ -0,0 +1,351 @@
+//! Resume support for partially-written destination files.
+//!
+//! Instead of scanning for the longest identical byte prefix, the destination
+//! is divided into fixed-size blocks (sized by the configured buffer size).
+//! Each block's hash is checked against the matching source block, and the
+//! first block that differs (or is only partially written) becomes the
+//! resume point. The block size and the last fully-verified offset are
+//! checkpointed to a sidecar file after every block, so a second interrupted
+//! run can skip the scan entirely - as long as the sidecar still matches the
+//! source it was written against (see [`Sidecar::source`]).
+
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+
+/// A source file's size and modification time, cheap to `stat` and specific
+/// enough to catch a source that's been replaced or edited since the sidecar
+/// was written - the case a bare `block_size` match can't detect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceFingerprint {
+    len: u64,
+    mtime_nanos: u128,
+}
+
+impl SourceFingerprint {
+    fn of(src_path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(src_path)?;
+        Ok(Self {
+            len: metadata.len(),
+            mtime_nanos: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos(),
+        })
+    }
+}
+
+/// Sidecar file recording how far a partial copy has been verified, so a
+/// resumed run can skip straight to `verified_offset` instead of re-scanning.
+pub struct Sidecar {
+    pub block_size: u64,
+    pub verified_offset: u64,
+    source: SourceFingerprint,
+}
+
+impl Sidecar {
+    /// `<dest_path>.mvvpart`, keyed by the final destination rather than the
+    /// `.mvvtmp` file it's checkpointing, so the sidecar's name doesn't change
+    /// as `move_file` switches between the fast-rename and streaming-copy paths.
+    pub fn path_for(dest_path: &Path) -> PathBuf {
+        let mut name = dest_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".mvvpart");
+        dest_path.with_file_name(name)
+    }
+
+    fn load(dest_path: &Path) -> Option<Self> {
+        let contents = std::fs::read_to_string(Self::path_for(dest_path)).ok()?;
+        let mut fields = contents.trim().split(' ');
+        Some(Self {
+            block_size: fields.next()?.parse().ok()?,
+            verified_offset: fields.next()?.parse().ok()?,
+            source: SourceFingerprint {
+                len: fields.next()?.parse().ok()?,
+                mtime_nanos: fields.next()?.parse().ok()?,
+            },
+        })
+    }
+
+    fn save(&self, dest_path: &Path) -> Result<()> {
+        std::fs::write(
+            Self::path_for(dest_path),
+            format!(
+                "{} {} {} {}",
+                self.block_size, self.verified_offset, self.source.len, self.source.mtime_nanos
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Removes the sidecar for `dest_path`, off the async runtime since this
+    /// is a synchronous `std::fs` call.
+    pub async fn delete(dest_path: &Path) -> Result<()> {
+        let dest_path = dest_path.to_path_buf();
+        tokio::task::spawn_blocking(move || match std::fs::remove_file(Self::path_for(&dest_path)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        })
+        .await?
+    }
+}
+
+/// Records that `dest_path`'s partial copy has been verified (or freshly
+/// copied) up to `verified_offset` at the given `block_size` against
+/// `src_path`'s current size and mtime, so a crash after this point can
+/// resume from here without rescanning - as long as `src_path` hasn't
+/// changed since. Runs on the blocking threadpool, since this writes the
+/// sidecar synchronously and is called once per flushed block on the hot
+/// copy path.
+pub async fn checkpoint(
+    src_path: &Path,
+    dest_path: &Path,
+    block_size: u64,
+    verified_offset: u64,
+) -> Result<()> {
+    let src_path = src_path.to_path_buf();
+    let dest_path = dest_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        Sidecar {
+            block_size,
+            verified_offset,
+            source: SourceFingerprint::of(&src_path)?,
+        }
+        .save(&dest_path)
+    })
+    .await?
+}
+
+/// Determines where a resumed copy should start writing. Trusts the sidecar
+/// at `dest_path` when present, built with the current `block_size`, and
+/// still matching `src_path`'s size and mtime; otherwise falls back to a
+/// full block-hash comparison of `write_path`'s partial content against the
+/// source. The fingerprint check is what keeps a stale sidecar - left over
+/// from an older transfer to the same destination, or from a source that was
+/// replaced mid-interruption - from being trusted just because the block
+/// size happens to match.
+pub async fn resume_offset(
+    src_path: &Path,
+    write_path: &Path,
+    dest_path: &Path,
+    block_size: u64,
+) -> Result<u64> {
+    let src_path = src_path.to_path_buf();
+    let write_path = write_path.to_path_buf();
+    let dest_path = dest_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        if let Some(sidecar) = Sidecar::load(&dest_path) {
+            if sidecar.block_size == block_size
+                && SourceFingerprint::of(&src_path).ok() == Some(sidecar.source)
+            {
+                return Ok(sidecar.verified_offset);
+            }
+        }
+
+        block_hash_scan(&src_path, &write_path, block_size)
+    })
+    .await?
+}
+
+/// Hashes matching `block_size` blocks from `src` and `write_path` and returns
+/// the offset of the first block that disagrees, that the source is missing,
+/// or that `write_path` has only partially written.
+fn block_hash_scan(src: &Path, write_path: &Path, block_size: u64) -> Result<u64> {
+    let mut src_file = std::fs::File::open(src)?;
+    let mut dest_file = std::fs::File::open(write_path)?;
+
+    let mut src_buf = vec![0u8; block_size as usize];
+    let mut dest_buf = vec![0u8; block_size as usize];
+    let mut offset = 0u64;
+
+    loop {
+        let dest_read = read_fill(&mut dest_file, &mut dest_buf)?;
+        if dest_read == 0 {
+            break;
+        }
+
+        let src_read = read_fill(&mut src_file, &mut src_buf)?;
+
+        // A short read on the destination's trailing block means it was never
+        // fully flushed - don't trust it, even if the bytes it does have match.
+        if dest_read < block_size as usize || src_read < dest_read {
+            break;
+        }
+
+        if seahash::hash(&src_buf[..src_read]) != seahash::hash(&dest_buf[..dest_read]) {
+            break;
+        }
+
+        offset += dest_read as u64;
+    }
+
+    Ok(offset)
+}
+
+/// Reads up to `buf.len()` bytes, stopping early only at EOF (unlike a single
+/// `read`, which may return short for other reasons).
+fn read_fill(file: &mut std::fs::File, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = file.read(&mut buf[read..])?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    /// A path under the system temp dir unique to this test invocation.
+    fn temp_path(name: &str) -> PathBuf {
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("mvv-resume-test-{}-{}-{name}", std::process::id(), id))
+    }
+
+    #[test]
+    fn path_for_is_keyed_by_dest_not_a_tmp_suffix() {
+        let dest = Path::new("/some/dir/file.bin");
+        assert_eq!(
+            Sidecar::path_for(dest),
+            PathBuf::from("/some/dir/file.bin.mvvpart")
+        );
+    }
+
+    #[test]
+    fn block_hash_scan_finds_the_first_differing_block() {
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBBCCCC").unwrap();
+        std::fs::write(&dest, b"AAAABBBBXXXX").unwrap();
+
+        let offset = block_hash_scan(&src, &dest, 4).unwrap();
+        assert_eq!(offset, 8);
+
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[test]
+    fn block_hash_scan_distrusts_a_short_trailing_block() {
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBB").unwrap();
+        // dest's second block only got 2 of 4 bytes flushed before a crash.
+        std::fs::write(&dest, b"AAAABB").unwrap();
+
+        let offset = block_hash_scan(&src, &dest, 4).unwrap();
+        assert_eq!(offset, 4);
+
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_offset_falls_back_to_scan_without_a_sidecar() {
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBB").unwrap();
+        std::fs::write(&dest, b"AAAABBBB").unwrap();
+
+        let offset = resume_offset(&src, &dest, &dest, 4).await.unwrap();
+        assert_eq!(offset, 8);
+
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_offset_trusts_a_matching_sidecar_without_rescanning() {
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBBCCCC").unwrap();
+        checkpoint(&src, &dest, 4, 12).await.unwrap();
+
+        // write_path doesn't exist at all - if resume_offset fell through to
+        // block_hash_scan it would error trying to open it.
+        let offset = resume_offset(&src, Path::new("/nonexistent-write"), &dest, 4)
+            .await
+            .unwrap();
+        assert_eq!(offset, 12);
+
+        Sidecar::delete(&dest).await.unwrap();
+        std::fs::remove_file(&src).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_offset_rescans_when_the_sidecars_block_size_changed() {
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBB").unwrap();
+        std::fs::write(&dest, b"AAAABBBB").unwrap();
+
+        checkpoint(&src, &dest, 999, 4).await.unwrap();
+
+        let offset = resume_offset(&src, &dest, &dest, 4).await.unwrap();
+        assert_eq!(offset, 8);
+
+        Sidecar::delete(&dest).await.unwrap();
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_offset_rescans_when_a_stale_sidecar_points_at_a_different_source() {
+        // Simulates a sidecar left behind by an older/unrelated transfer to the
+        // same destination name: block_size matches, but the source it was
+        // checkpointed against is gone, replaced by one with different content.
+        let old_src = temp_path("old-src");
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&old_src, b"AAAABBBB").unwrap();
+        checkpoint(&old_src, &dest, 4, 8).await.unwrap();
+        std::fs::remove_file(&old_src).unwrap();
+
+        std::fs::write(&src, b"11112222333344445555").unwrap();
+        std::fs::write(&dest, b"1111222233334444").unwrap();
+
+        // Must rescan rather than blindly trusting the stale 8-byte checkpoint -
+        // write_path and src actually agree for the first 16 bytes.
+        let offset = resume_offset(&src, &dest, &dest, 4).await.unwrap();
+        assert_eq!(offset, 16);
+
+        Sidecar::delete(&dest).await.unwrap();
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resume_offset_rescans_when_the_source_was_modified_since_the_checkpoint() {
+        // Simulates: interrupt a copy, then edit the source before resuming.
+        let src = temp_path("src");
+        let dest = temp_path("dest");
+        std::fs::write(&src, b"AAAABBBB").unwrap();
+        std::fs::write(&dest, b"AAAABBBB").unwrap();
+        checkpoint(&src, &dest, 4, 8).await.unwrap();
+
+        // Same length, different content and a fresh mtime - the sidecar's
+        // fingerprint no longer matches, even though block_size does.
+        std::fs::write(&src, b"AAAAXXXX").unwrap();
+
+        let offset = resume_offset(&src, &dest, &dest, 4).await.unwrap();
+        assert_eq!(offset, 4);
+
+        Sidecar::delete(&dest).await.unwrap();
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sidecar_delete_is_idempotent_when_missing() {
+        let dest = temp_path("missing");
+        Sidecar::delete(&dest).await.unwrap();
+        Sidecar::delete(&dest).await.unwrap();
+    }
+}
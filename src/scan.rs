@@ -0,0 +1,85 @@
+//! Asynchronous directory scanning.
+//!
+//! `WalkDir` is synchronous, so the scan runs on the blocking threadpool and
+//! feeds discovered entries into the worker pool through a bounded channel as
+//! they're found. That lets copies start immediately instead of waiting for
+//! the whole tree to be walked first, and keeps memory bounded on directories
+//! with hundreds of thousands of entries.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use indicatif::ProgressBar;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use walkdir::WalkDir;
+
+/// Bound on how many discovered-but-not-yet-dispatched entries can queue up
+/// before the scan blocks, capping memory on huge trees.
+const CHANNEL_CAPACITY: usize = 256;
+
+pub enum Entry {
+    File { src: PathBuf, dest: PathBuf },
+    Symlink { src: PathBuf, dest: PathBuf },
+}
+
+/// Spawns a blocking scan task over `src` (mirrored onto `dest`) and returns a
+/// channel of discovered entries alongside the task's join handle. `summary_bar`
+/// is updated with a running discovered-file count and finished once the walk
+/// completes.
+pub fn spawn(
+    src: PathBuf,
+    dest: PathBuf,
+    src_is_file: bool,
+    max_depth: Option<usize>,
+    summary_bar: ProgressBar,
+) -> (mpsc::Receiver<Entry>, JoinHandle<Result<()>>) {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+
+    let handle = tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut walker = WalkDir::new(&src);
+        if let Some(max_depth) = max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+
+        let mut discovered = 0u64;
+        for entry in walker {
+            let entry = entry?;
+
+            let (entry_src, entry_dest) = if src_is_file {
+                (src.clone(), dest.clone())
+            } else {
+                let rel_path = entry.path().strip_prefix(&src)?;
+                (src.join(rel_path), dest.join(rel_path))
+            };
+
+            let scanned = if entry.file_type().is_symlink() {
+                Some(Entry::Symlink {
+                    src: entry_src,
+                    dest: entry_dest,
+                })
+            } else if entry.file_type().is_file() {
+                Some(Entry::File {
+                    src: entry_src,
+                    dest: entry_dest,
+                })
+            } else {
+                None
+            };
+
+            if let Some(scanned) = scanned {
+                discovered += 1;
+                summary_bar.set_message(format!("discovered {discovered} files"));
+                if tx.blocking_send(scanned).is_err() {
+                    // Receiver dropped (e.g. the run is being aborted); stop walking.
+                    break;
+                }
+            }
+        }
+
+        summary_bar.finish_with_message(format!("scan complete: {discovered} files discovered"));
+        Ok(())
+    });
+
+    (rx, handle)
+}